@@ -0,0 +1,109 @@
+//! Signed, replay-safe control commands.
+//!
+//! An untrusted front door can relay control-plane changes by POSTing a
+//! [`CommandEnvelope`] to `/api/command`. When a verifying key is configured
+//! (env `CONTROL_VERIFYING_KEY`, SEC1-encoded hex) the `command` field is
+//! re-serialized canonically, the detached ECDSA P-384 signature is checked
+//! against it, and the command's `nonce` must strictly exceed the highest one
+//! seen so far — rejecting stale and replayed envelopes. With no key
+//! configured the envelope is executed unauthenticated, preserving the
+//! original behavior.
+
+use std::sync::Arc;
+
+use axum::Json;
+use p384::ecdsa::signature::Verifier;
+use p384::ecdsa::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::{do_create, do_delete, do_set_enabled, AppState};
+
+/// A control command plus the nonce that makes each signing unique.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command")]
+pub enum Command {
+    CreateProxy { domain: String, port: u16, nonce: u64 },
+    Enable { id: i64, nonce: u64 },
+    Disable { id: i64, nonce: u64 },
+    Delete { id: i64, nonce: u64 },
+}
+
+impl Command {
+    fn nonce(&self) -> u64 {
+        match self {
+            Command::CreateProxy { nonce, .. }
+            | Command::Enable { nonce, .. }
+            | Command::Disable { nonce, .. }
+            | Command::Delete { nonce, .. } => *nonce,
+        }
+    }
+}
+
+/// Wire envelope: the command and its detached signature bytes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandEnvelope {
+    command: Command,
+    #[serde(default)]
+    signature: Vec<u8>,
+}
+
+/// Parse a SEC1-encoded hex public key into a [`VerifyingKey`].
+pub fn parse_verifying_key(hex: &str) -> anyhow::Result<VerifyingKey> {
+    let bytes = hex::decode(hex.trim())?;
+    let key = VerifyingKey::from_sec1_bytes(&bytes)?;
+    Ok(key)
+}
+
+/// Verify (if a key is configured), check the nonce, then dispatch.
+pub async fn execute(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    Json(envelope): Json<CommandEnvelope>,
+) -> Result<Json<serde_json::Value>, Error> {
+    if let Some(key) = state.verifying_key.as_ref() {
+        // Re-serialize the command canonically and verify the detached signature.
+        let message =
+            serde_json::to_vec(&envelope.command).map_err(|e| Error::Internal(e.to_string()))?;
+        let signature =
+            Signature::from_slice(&envelope.signature).map_err(|_| Error::Unauthorized)?;
+        key.verify(&message, &signature)
+            .map_err(|_| Error::Unauthorized)?;
+
+        // Replay guard: nonces must strictly increase. Update the in-memory
+        // value under the lock, then persist it (dropping the guard first so we
+        // never hold it across the await).
+        let nonce = envelope.command.nonce();
+        {
+            let mut last = state.last_nonce.write().unwrap();
+            if nonce <= *last {
+                return Err(Error::Unauthorized);
+            }
+            *last = nonce;
+        }
+        state.store.store_nonce(nonce).await?;
+    }
+
+    dispatch(&state, envelope.command).await.map(Json)
+}
+
+/// Run the command against the shared mutation helpers.
+async fn dispatch(state: &AppState, command: Command) -> Result<serde_json::Value, Error> {
+    match command {
+        Command::CreateProxy { domain, port, .. } => {
+            let proxy = do_create(state, domain, port).await?;
+            Ok(serde_json::json!(proxy))
+        }
+        Command::Enable { id, .. } => {
+            let proxy = do_set_enabled(state, id, true).await?;
+            Ok(serde_json::json!(proxy))
+        }
+        Command::Disable { id, .. } => {
+            let proxy = do_set_enabled(state, id, false).await?;
+            Ok(serde_json::json!(proxy))
+        }
+        Command::Delete { id, .. } => {
+            do_delete(state, id).await?;
+            Ok(serde_json::json!({ "deleted": id }))
+        }
+    }
+}
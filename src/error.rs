@@ -0,0 +1,47 @@
+//! Structured API errors.
+//!
+//! Handlers return `Result<_, Error>`; Axum renders the [`Error`] through
+//! [`IntoResponse`], mapping each variant to its proper [`StatusCode`] and a
+//! JSON body of the form `{ "error": "..." }` so consumers get machine-readable,
+//! correctly-coded failures.
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Proxy not found")]
+    NotFound,
+    #[error("Domain already exists")]
+    DuplicateDomain,
+    #[error("Invalid port")]
+    InvalidPort,
+    #[error("{0}")]
+    InvalidToxic(&'static str),
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl Error {
+    fn status(&self) -> StatusCode {
+        match self {
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::DuplicateDomain => StatusCode::CONFLICT,
+            Error::InvalidPort | Error::InvalidToxic(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Database(_) | Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (self.status(), Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
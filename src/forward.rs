@@ -0,0 +1,142 @@
+//! Reverse-proxy data plane.
+//!
+//! A single public-edge [`TcpListener`] accepts client connections, reads the
+//! `Host` header of the incoming request, and looks the domain up in the shared
+//! [`DashMap`] of proxies. Because the map is the same `Arc` the CRUD handlers
+//! mutate, routing reacts to `create`/`delete`/`enable`/`disable` live — no
+//! restart required. Matched, enabled domains are forwarded to
+//! `127.0.0.1:<port>` with a bidirectional byte copy; everything else gets a
+//! small HTTP error back.
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::toxics::{pump, Shaper};
+use crate::Proxy;
+
+/// Largest header block we will buffer while sniffing the `Host` header.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// Bind the public edge and forward matching traffic forever.
+///
+/// Errors from individual connections are logged and swallowed so one bad
+/// client never takes the listener down.
+pub async fn run_edge(addr: std::net::SocketAddr, proxies: Arc<DashMap<String, Proxy>>) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!("edge listener failed to bind {}: {}", addr, e);
+            return;
+        }
+    };
+    tracing::info!("edge listening on {}", addr);
+
+    loop {
+        let (inbound, peer) = match listener.accept().await {
+            Ok(pair) => pair,
+            Err(e) => {
+                tracing::warn!("edge accept error: {}", e);
+                continue;
+            }
+        };
+        let proxies = proxies.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(inbound, proxies).await {
+                tracing::debug!("edge connection from {} ended: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Read enough of the request to find its `Host`, resolve the upstream, and
+/// splice the two streams together.
+async fn handle_connection(
+    mut inbound: TcpStream,
+    proxies: Arc<DashMap<String, Proxy>>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::with_capacity(4096);
+    let mut chunk = [0u8; 4096];
+
+    let host = loop {
+        let n = inbound.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(host) = parse_host(&buf) {
+            break host;
+        }
+        if buf.len() >= MAX_HEADER_BYTES {
+            return respond(&mut inbound, 400, "Bad Request").await;
+        }
+    };
+
+    // Snapshot port and toxics so we hold no map guard across the splice.
+    let (upstream_port, shaper) = match proxies.get(&host) {
+        Some(entry) if entry.enabled => (entry.port, Shaper::from_toxics(&entry.toxics)),
+        Some(_) => return respond(&mut inbound, 502, "Bad Gateway").await,
+        None => return respond(&mut inbound, 404, "Not Found").await,
+    };
+
+    let mut upstream = match TcpStream::connect(("127.0.0.1", upstream_port)).await {
+        Ok(s) => s,
+        Err(_) => return respond(&mut inbound, 502, "Bad Gateway").await,
+    };
+
+    // Replay the bytes we already consumed while sniffing the Host header.
+    upstream.write_all(&buf).await?;
+
+    let spliced = splice(&mut inbound, &mut upstream, &shaper);
+    match shaper.timeout() {
+        Some(limit) => match tokio::time::timeout(limit, spliced).await {
+            Ok(res) => res,
+            // `timeout` toxic: drop the connection once the limit elapses.
+            Err(_) => Ok(()),
+        },
+        None => spliced.await,
+    }
+}
+
+/// Run both directions of the splice concurrently, applying the shaper to each.
+async fn splice(
+    inbound: &mut TcpStream,
+    upstream: &mut TcpStream,
+    shaper: &Shaper,
+) -> std::io::Result<()> {
+    let (mut ri, mut wi) = inbound.split();
+    let (mut ru, mut wu) = upstream.split();
+    let client_to_upstream = pump(&mut ri, &mut wu, shaper);
+    let upstream_to_client = pump(&mut ru, &mut wi, shaper);
+    tokio::try_join!(client_to_upstream, upstream_to_client)?;
+    Ok(())
+}
+
+/// Extract the lowercased host (port stripped) from a buffered HTTP request,
+/// or `None` if the header block is not complete yet.
+fn parse_host(buf: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(buf).ok()?;
+    let header_end = text.find("\r\n\r\n")?;
+    for line in text[..header_end].split("\r\n") {
+        if let Some(value) = line.split_once(':') {
+            if value.0.eq_ignore_ascii_case("host") {
+                let host = value.1.trim();
+                let host = host.split(':').next().unwrap_or(host);
+                return Some(host.to_ascii_lowercase());
+            }
+        }
+    }
+    // Headers are complete but carried no Host.
+    Some(String::new())
+}
+
+/// Write a minimal HTTP error response and drop the connection.
+async fn respond(inbound: &mut TcpStream, status: u16, reason: &str) -> std::io::Result<()> {
+    let body = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    );
+    inbound.write_all(body.as_bytes()).await?;
+    inbound.flush().await
+}
@@ -0,0 +1,63 @@
+//! Background upstream health probing.
+//!
+//! Every [`PROBE_INTERVAL`] the prober TCP-connects to each enabled proxy's
+//! upstream within [`PROBE_TIMEOUT`], records the resulting [`ProxyState`] back
+//! onto the [`DashMap`] entry (so `list_proxies` reflects current health), and
+//! broadcasts a [`ServerEvent::ProxyStatusUpdated`] whenever a proxy's state
+//! actually changes.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+use crate::{Proxy, ProxyState, ProxyStatus, ServerEvent};
+
+/// How often the prober sweeps every enabled proxy.
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a single upstream connect may take before it counts as down.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Probe every enabled proxy forever, updating state and emitting events.
+pub async fn run_prober(
+    proxies: Arc<DashMap<String, Proxy>>,
+    events: broadcast::Sender<ServerEvent>,
+) {
+    let mut ticker = tokio::time::interval(PROBE_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        // Snapshot the ports first so we hold no map guard across the awaits.
+        let targets: Vec<(String, u16)> = proxies
+            .iter()
+            .filter(|e| e.enabled)
+            .map(|e| (e.key().clone(), e.port))
+            .collect();
+
+        for (domain, port) in targets {
+            let state = probe(port).await;
+            if let Some(mut entry) = proxies.get_mut(&domain) {
+                if entry.status.state != state {
+                    entry.status.state = state;
+                    let id = entry.id;
+                    drop(entry);
+                    let _ = events.send(ServerEvent::ProxyStatusUpdated {
+                        id,
+                        status: ProxyStatus { state },
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Connect to `127.0.0.1:<port>` within the timeout and map the outcome.
+async fn probe(port: u16) -> ProxyState {
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(("127.0.0.1", port))).await {
+        Ok(Ok(_)) => ProxyState::Active,
+        _ => ProxyState::Inactive,
+    }
+}
@@ -5,18 +5,65 @@ use axum::{
 };
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use sqlx::{SqlitePool, Row};
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tracing_subscriber;
 
+mod command;
+mod error;
+mod forward;
+mod health;
+mod store;
+mod toxics;
+
+use error::Error;
+use store::{MemoryStore, ProxyStore, SqliteStore};
+use toxics::Toxic;
+
+use p384::ecdsa::VerifyingKey;
+use std::sync::RwLock;
+
+/// Reachability of a proxy's upstream, as observed by the health prober.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum ProxyState {
+    Active,
+    Inactive,
+    Unknown,
+}
+
+impl Default for ProxyState {
+    fn default() -> Self {
+        ProxyState::Unknown
+    }
+}
+
+/// Health of a proxy's upstream target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct ProxyStatus {
+    state: ProxyState,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Proxy {
     id: i64,
     domain: String,
     port: u16,
     enabled: bool,
+    #[serde(default)]
+    status: ProxyStatus,
+    #[serde(default)]
+    toxics: Vec<Toxic>,
+}
+
+/// Control-plane events broadcast to connected dashboards.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+enum ServerEvent {
+    ProxiesUpdated,
+    ProxyStatusUpdated { id: i64, status: ProxyStatus },
+    ProxyDeleted { id: i64 },
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -26,8 +73,16 @@ struct CreateProxy {
 }
 
 struct AppState {
-    db: SqlitePool,
+    store: Arc<dyn ProxyStore>,
     proxies: Arc<DashMap<String, Proxy>>,
+    events: broadcast::Sender<ServerEvent>,
+    /// ECDSA P-384 key that signed commands are verified against. `None`
+    /// leaves the mutating API unauthenticated (the original behavior).
+    verifying_key: Option<VerifyingKey>,
+    /// Highest nonce accepted so far for the configured key; signed commands
+    /// must strictly exceed it, which rejects stale and replayed envelopes.
+    /// Seeded from and persisted to the store so it survives restarts.
+    last_nonce: RwLock<u64>,
 }
 
 #[tokio::main]
@@ -35,32 +90,31 @@ async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
     dotenvy::dotenv().ok();
 
-    let db = SqlitePool::connect("sqlite://tunnelfy.db").await?;
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS proxies (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            domain TEXT NOT NULL UNIQUE,
-            port INTEGER NOT NULL,
-            enabled BOOLEAN NOT NULL DEFAULT 1
-        )"
-    ).execute(&db).await?;
+    // Select the storage backend. `STORE=memory` runs fully in-memory for
+    // ephemeral deployments; anything else uses the SQLite file.
+    let store: Arc<dyn ProxyStore> = match env::var("STORE").as_deref() {
+        Ok("memory") => Arc::new(MemoryStore::default()),
+        _ => Arc::new(SqliteStore::connect("sqlite://tunnelfy.db").await?),
+    };
 
+    let (events, _) = broadcast::channel(256);
+    let verifying_key = match env::var("CONTROL_VERIFYING_KEY") {
+        Ok(hex) => Some(command::parse_verifying_key(&hex)?),
+        Err(_) => None,
+    };
+    // Seed the replay guard from durable state so spent nonces stay rejected
+    // across restarts.
+    let last_nonce = store.load_nonce().await?;
     let state = Arc::new(AppState {
-        db: db.clone(),
+        store: store.clone(),
         proxies: Arc::new(DashMap::new()),
+        events,
+        verifying_key,
+        last_nonce: RwLock::new(last_nonce),
     });
 
-    // Load existing proxies from DB
-    let rows = sqlx::query("SELECT id, domain, port, enabled FROM proxies")
-        .fetch_all(&db)
-        .await?;
-    for row in rows {
-        let proxy = Proxy {
-            id: row.get(0),
-            domain: row.get(1),
-            port: row.get(2),
-            enabled: row.get(3),
-        };
+    // Seed the in-memory routing table from the store.
+    for proxy in store.list().await? {
         state.proxies.insert(proxy.domain.clone(), proxy);
     }
 
@@ -68,18 +122,11 @@ async fn main() -> anyhow::Result<()> {
     if let Ok(static_proxies) = env::var("STATIC_PROXIES") {
         for entry in static_proxies.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
             if let Some((domain, port_str)) = entry.split_once(':') {
+                let domain = domain.to_ascii_lowercase();
                 if let Ok(port) = port_str.parse::<u16>() {
-                    if !state.proxies.contains_key(domain) {
-                        let res = sqlx::query(
-                            "INSERT INTO proxies (domain, port, enabled) VALUES (?, ?, 1)"
-                        )
-                        .bind(domain)
-                        .bind(port as i64)
-                        .execute(&db)
-                        .await?;
-
-                        let id = res.last_insert_rowid();
-                        let proxy = Proxy { id, domain: domain.to_string(), port, enabled: true };
+                    if !state.proxies.contains_key(&domain) {
+                        let id = store.insert(&domain, port).await?;
+                        let proxy = Proxy { id, domain: domain.clone(), port, enabled: true, status: ProxyStatus::default(), toxics: Vec::new() };
                         state.proxies.insert(proxy.domain.clone(), proxy);
                         tracing::info!("Inserted static proxy: {} -> {}", domain, port);
                     }
@@ -88,12 +135,34 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    // Spawn the public-edge data plane. It shares the same proxies map the CRUD
+    // handlers mutate, so routing changes take effect without a restart.
+    let edge_addr: SocketAddr = env::var("EDGE_ADDR")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 80)));
+    {
+        let proxies = state.proxies.clone();
+        tokio::spawn(async move { forward::run_edge(edge_addr, proxies).await });
+    }
+
+    // Periodically probe upstreams and broadcast status changes.
+    {
+        let proxies = state.proxies.clone();
+        let events = state.events.clone();
+        tokio::spawn(async move { health::run_prober(proxies, events).await });
+    }
+
     let app = Router::new()
         .route("/api/proxies", post(create_proxy))
         .route("/api/proxies/:id/enable", post(enable_proxy))
         .route("/api/proxies/:id/disable", post(disable_proxy))
         .route("/api/proxies/:id", delete(delete_proxy))
         .route("/api/proxies", get(list_proxies))
+        .route("/api/events", get(events_stream))
+        .route("/api/command", post(command::execute))
+        .route("/api/proxies/:id/toxics", post(add_toxic))
+        .route("/api/proxies/:id/toxics/:name", delete(delete_toxic))
         .with_state(state);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
@@ -105,79 +174,136 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn create_proxy(
-    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
-    Json(payload): Json<CreateProxy>,
-) -> Result<Json<Proxy>, String> {
-    let res = sqlx::query(
-        "INSERT INTO proxies (domain, port, enabled) VALUES (?, ?, 1)"
-    )
-    .bind(&payload.domain)
-    .bind(payload.port as i64)
-    .execute(&state.db)
-    .await
-    .map_err(|e| e.to_string())?;
-
-    let id = res.last_insert_rowid();
-    let proxy = Proxy { id, domain: payload.domain, port: payload.port, enabled: true };
+// Core mutation operations, shared by the REST handlers and the signed-command
+// relay in `command`. Each keeps the store and the in-memory map in sync
+// and publishes the relevant `ServerEvent`.
+
+async fn do_create(state: &AppState, domain: String, port: u16) -> Result<Proxy, Error> {
+    if port == 0 {
+        return Err(Error::InvalidPort);
+    }
+    // Normalize the domain so the map key and persisted row match the
+    // lowercased Host the data plane looks up.
+    let domain = domain.to_ascii_lowercase();
+    let id = state.store.insert(&domain, port).await?;
+    let proxy = Proxy { id, domain, port, enabled: true, status: ProxyStatus::default(), toxics: Vec::new() };
     state.proxies.insert(proxy.domain.clone(), proxy.clone());
-    Ok(Json(proxy))
+    let _ = state.events.send(ServerEvent::ProxiesUpdated);
+    Ok(proxy)
 }
 
-async fn delete_proxy(
-    Path(id): Path<i64>,
-    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
-) -> Result<Json<String>, String> {
-    let result = sqlx::query("DELETE FROM proxies WHERE id = ?")
-        .bind(id)
-        .execute(&state.db)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if result.rows_affected() == 0 {
-        return Err("Proxy not found".to_string());
+async fn do_delete(state: &AppState, id: i64) -> Result<(), Error> {
+    if !state.store.delete(id).await? {
+        return Err(Error::NotFound);
     }
 
-    if let Some(entry) = state.proxies.iter().find(|p| p.value().id == id) {
-        let domain = entry.domain.clone();
+    // Resolve the key and fully drop the iterator (at the `;`) before taking a
+    // write lock on the same shard — holding the read guard across `remove`
+    // would deadlock the worker.
+    let domain = state
+        .proxies
+        .iter()
+        .find(|p| p.value().id == id)
+        .map(|e| e.domain.clone());
+    if let Some(domain) = domain {
         state.proxies.remove(&domain);
     }
 
+    let _ = state.events.send(ServerEvent::ProxyDeleted { id });
+    Ok(())
+}
+
+async fn do_set_enabled(state: &AppState, id: i64, enabled: bool) -> Result<Proxy, Error> {
+    state.store.set_enabled(id, enabled).await?;
+
+    if let Some(mut proxy) = state.proxies.iter_mut().find(|p| p.value().id == id) {
+        proxy.enabled = enabled;
+        // A disabled proxy is no longer probed, so clear its health to Unknown
+        // rather than leaving a stale Active showing in list/SSE.
+        if !enabled {
+            proxy.status.state = ProxyState::Unknown;
+        }
+        let out = proxy.clone();
+        drop(proxy);
+        let _ = state.events.send(ServerEvent::ProxiesUpdated);
+        return Ok(out);
+    }
+    Err(Error::NotFound)
+}
+
+/// Persist the current toxic set of a proxy through the configured store.
+async fn persist_toxics(state: &AppState, id: i64, toxics: &[Toxic]) -> Result<(), Error> {
+    state.store.set_toxics(id, toxics).await
+}
+
+async fn add_toxic(
+    Path(id): Path<i64>,
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    Json(toxic): Json<Toxic>,
+) -> Result<Json<Proxy>, Error> {
+    toxic.validate().map_err(Error::InvalidToxic)?;
+    let out = {
+        let mut proxy = state
+            .proxies
+            .iter_mut()
+            .find(|p| p.value().id == id)
+            .ok_or(Error::NotFound)?;
+        // Replace any existing toxic of the same name so each kind is unique.
+        let name = toxic.name();
+        proxy.toxics.retain(|t| t.name() != name);
+        proxy.toxics.push(toxic);
+        proxy.clone()
+    };
+    persist_toxics(&state, id, &out.toxics).await?;
+    let _ = state.events.send(ServerEvent::ProxiesUpdated);
+    Ok(Json(out))
+}
+
+async fn delete_toxic(
+    Path((id, name)): Path<(i64, String)>,
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+) -> Result<Json<Proxy>, Error> {
+    let out = {
+        let mut proxy = state
+            .proxies
+            .iter_mut()
+            .find(|p| p.value().id == id)
+            .ok_or(Error::NotFound)?;
+        proxy.toxics.retain(|t| t.name() != name);
+        proxy.clone()
+    };
+    persist_toxics(&state, id, &out.toxics).await?;
+    let _ = state.events.send(ServerEvent::ProxiesUpdated);
+    Ok(Json(out))
+}
+
+async fn create_proxy(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    Json(payload): Json<CreateProxy>,
+) -> Result<Json<Proxy>, Error> {
+    Ok(Json(do_create(&state, payload.domain, payload.port).await?))
+}
+
+async fn delete_proxy(
+    Path(id): Path<i64>,
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+) -> Result<Json<String>, Error> {
+    do_delete(&state, id).await?;
     Ok(Json(format!("Proxy {} deleted", id)))
 }
 
 async fn enable_proxy(
     Path(id): Path<i64>,
     axum::extract::State(state): axum::extract::State<Arc<AppState>>,
-) -> Result<Json<Proxy>, String> {
-    sqlx::query("UPDATE proxies SET enabled = 1 WHERE id = ?")
-        .bind(id)
-        .execute(&state.db)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if let Some(mut proxy) = state.proxies.iter_mut().find(|p| p.value().id == id) {
-        proxy.enabled = true;
-        return Ok(Json(proxy.clone()));
-    }
-    Err("Proxy not found".to_string())
+) -> Result<Json<Proxy>, Error> {
+    Ok(Json(do_set_enabled(&state, id, true).await?))
 }
 
 async fn disable_proxy(
     Path(id): Path<i64>,
     axum::extract::State(state): axum::extract::State<Arc<AppState>>,
-) -> Result<Json<Proxy>, String> {
-    sqlx::query("UPDATE proxies SET enabled = 0 WHERE id = ?")
-        .bind(id)
-        .execute(&state.db)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if let Some(mut proxy) = state.proxies.iter_mut().find(|p| p.value().id == id) {
-        proxy.enabled = false;
-        return Ok(Json(proxy.clone()));
-    }
-    Err("Proxy not found".to_string())
+) -> Result<Json<Proxy>, Error> {
+    Ok(Json(do_set_enabled(&state, id, false).await?))
 }
 
 async fn list_proxies(
@@ -186,3 +312,79 @@ async fn list_proxies(
     let proxies: Vec<Proxy> = state.proxies.iter().map(|e| e.value().clone()).collect();
     Json(proxies)
 }
+
+async fn events_stream(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+) -> axum::response::Sse<impl futures::Stream<Item = Result<axum::response::sse::Event, std::convert::Infallible>>> {
+    use axum::response::sse::{Event, Sse};
+    use futures::StreamExt;
+    use tokio_stream::wrappers::BroadcastStream;
+
+    let stream = BroadcastStream::new(state.events.subscribe()).filter_map(|item| async move {
+        let event = item.ok()?;
+        Some(Ok(Event::default().json_data(&event).unwrap_or_default()))
+    });
+
+    Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> Arc<AppState> {
+        let (events, _) = broadcast::channel(16);
+        Arc::new(AppState {
+            store: Arc::new(MemoryStore::default()),
+            proxies: Arc::new(DashMap::new()),
+            events,
+            verifying_key: None,
+            last_nonce: RwLock::new(0),
+        })
+    }
+
+    #[tokio::test]
+    async fn create_lowercases_and_rejects_duplicates() {
+        let state = test_state();
+        let proxy = do_create(&state, "API.Example.com".into(), 9000).await.unwrap();
+        assert_eq!(proxy.domain, "api.example.com");
+        assert!(state.proxies.contains_key("api.example.com"));
+
+        let err = do_create(&state, "api.example.com".into(), 9001).await.unwrap_err();
+        assert!(matches!(err, Error::DuplicateDomain));
+    }
+
+    #[tokio::test]
+    async fn create_rejects_zero_port() {
+        let state = test_state();
+        let err = do_create(&state, "a.example.com".into(), 0).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidPort));
+    }
+
+    #[tokio::test]
+    async fn enable_disable_persist_through_store() {
+        let state = test_state();
+        let proxy = do_create(&state, "b.example.com".into(), 8000).await.unwrap();
+
+        let disabled = do_set_enabled(&state, proxy.id, false).await.unwrap();
+        assert!(!disabled.enabled);
+        let enabled = do_set_enabled(&state, proxy.id, true).await.unwrap();
+        assert!(enabled.enabled);
+
+        let persisted = state.store.list().await.unwrap();
+        assert!(persisted.iter().any(|p| p.id == proxy.id && p.enabled));
+    }
+
+    #[tokio::test]
+    async fn delete_removes_and_is_idempotent_error() {
+        let state = test_state();
+        let proxy = do_create(&state, "c.example.com".into(), 8001).await.unwrap();
+
+        do_delete(&state, proxy.id).await.unwrap();
+        assert!(!state.proxies.contains_key("c.example.com"));
+        assert!(state.store.list().await.unwrap().is_empty());
+
+        let err = do_delete(&state, proxy.id).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound));
+    }
+}
@@ -0,0 +1,225 @@
+//! Pluggable persistence behind the [`ProxyStore`] trait.
+//!
+//! The handlers own the in-memory [`DashMap`](dashmap::DashMap) routing table
+//! and delegate durability to a `ProxyStore`. [`SqliteStore`] wraps the
+//! original sqlx queries; [`MemoryStore`] keeps everything in a `Mutex` so the
+//! test suite and ephemeral deployments can run without a database file.
+
+use async_trait::async_trait;
+use sqlx::{Row, SqlitePool};
+use std::sync::Mutex;
+
+use crate::error::Error;
+use crate::toxics::Toxic;
+use crate::{Proxy, ProxyStatus};
+
+/// Durable storage for proxy definitions.
+#[async_trait]
+pub trait ProxyStore: Send + Sync {
+    /// All persisted proxies, used to seed the in-memory table at startup.
+    async fn list(&self) -> Result<Vec<Proxy>, Error>;
+    /// Insert a new enabled proxy and return its assigned id.
+    async fn insert(&self, domain: &str, port: u16) -> Result<i64, Error>;
+    /// Delete by id; returns whether a row actually existed.
+    async fn delete(&self, id: i64) -> Result<bool, Error>;
+    /// Flip the enabled flag.
+    async fn set_enabled(&self, id: i64, enabled: bool) -> Result<(), Error>;
+    /// Replace the stored toxic set.
+    async fn set_toxics(&self, id: i64, toxics: &[Toxic]) -> Result<(), Error>;
+    /// Highest signed-command nonce spent so far (0 if none), used to make the
+    /// replay guard durable across restarts.
+    async fn load_nonce(&self) -> Result<u64, Error>;
+    /// Persist the highest spent nonce.
+    async fn store_nonce(&self, nonce: u64) -> Result<(), Error>;
+}
+
+/// SQLite-backed store wrapping the crate's original queries.
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Connect, run the schema migration, and return a ready store.
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let pool = SqlitePool::connect(url).await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS proxies (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                domain TEXT NOT NULL UNIQUE,
+                port INTEGER NOT NULL,
+                enabled BOOLEAN NOT NULL DEFAULT 1,
+                toxics TEXT NOT NULL DEFAULT '[]'
+            )",
+        )
+        .execute(&pool)
+        .await?;
+        // Best-effort migration for databases created before the toxics column.
+        let _ = sqlx::query("ALTER TABLE proxies ADD COLUMN toxics TEXT NOT NULL DEFAULT '[]'")
+            .execute(&pool)
+            .await;
+        // Durable key/value state for the control-plane replay guard.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS control_state (
+                key TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl ProxyStore for SqliteStore {
+    async fn list(&self) -> Result<Vec<Proxy>, Error> {
+        let rows = sqlx::query("SELECT id, domain, port, enabled, toxics FROM proxies")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let toxics_json: String = row.get(4);
+                Proxy {
+                    id: row.get(0),
+                    domain: row.get(1),
+                    port: row.get(2),
+                    enabled: row.get(3),
+                    status: ProxyStatus::default(),
+                    toxics: serde_json::from_str(&toxics_json).unwrap_or_default(),
+                }
+            })
+            .collect())
+    }
+
+    async fn insert(&self, domain: &str, port: u16) -> Result<i64, Error> {
+        let res = sqlx::query("INSERT INTO proxies (domain, port, enabled) VALUES (?, ?, 1)")
+            .bind(domain)
+            .bind(port as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match e.as_database_error() {
+                Some(db) if db.is_unique_violation() => Error::DuplicateDomain,
+                _ => Error::Database(e),
+            })?;
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn delete(&self, id: i64) -> Result<bool, Error> {
+        let res = sqlx::query("DELETE FROM proxies WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn set_enabled(&self, id: i64, enabled: bool) -> Result<(), Error> {
+        sqlx::query("UPDATE proxies SET enabled = ? WHERE id = ?")
+            .bind(enabled)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_toxics(&self, id: i64, toxics: &[Toxic]) -> Result<(), Error> {
+        let json = serde_json::to_string(toxics).map_err(|e| Error::Internal(e.to_string()))?;
+        sqlx::query("UPDATE proxies SET toxics = ? WHERE id = ?")
+            .bind(json)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_nonce(&self) -> Result<u64, Error> {
+        let row = sqlx::query("SELECT value FROM control_state WHERE key = 'nonce'")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get::<i64, _>(0) as u64).unwrap_or(0))
+    }
+
+    async fn store_nonce(&self, nonce: u64) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO control_state (key, value) VALUES ('nonce', ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        )
+        .bind(nonce as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// In-memory store for tests and ephemeral deployments.
+pub struct MemoryStore {
+    rows: Mutex<Vec<Proxy>>,
+    next_id: Mutex<i64>,
+    nonce: Mutex<u64>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            rows: Mutex::new(Vec::new()),
+            next_id: Mutex::new(1),
+            nonce: Mutex::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl ProxyStore for MemoryStore {
+    async fn list(&self) -> Result<Vec<Proxy>, Error> {
+        Ok(self.rows.lock().unwrap().clone())
+    }
+
+    async fn insert(&self, domain: &str, port: u16) -> Result<i64, Error> {
+        let mut rows = self.rows.lock().unwrap();
+        if rows.iter().any(|p| p.domain == domain) {
+            return Err(Error::DuplicateDomain);
+        }
+        let mut next = self.next_id.lock().unwrap();
+        let id = *next;
+        *next += 1;
+        rows.push(Proxy {
+            id,
+            domain: domain.to_string(),
+            port,
+            enabled: true,
+            status: ProxyStatus::default(),
+            toxics: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    async fn delete(&self, id: i64) -> Result<bool, Error> {
+        let mut rows = self.rows.lock().unwrap();
+        let before = rows.len();
+        rows.retain(|p| p.id != id);
+        Ok(rows.len() != before)
+    }
+
+    async fn set_enabled(&self, id: i64, enabled: bool) -> Result<(), Error> {
+        if let Some(proxy) = self.rows.lock().unwrap().iter_mut().find(|p| p.id == id) {
+            proxy.enabled = enabled;
+        }
+        Ok(())
+    }
+
+    async fn set_toxics(&self, id: i64, toxics: &[Toxic]) -> Result<(), Error> {
+        if let Some(proxy) = self.rows.lock().unwrap().iter_mut().find(|p| p.id == id) {
+            proxy.toxics = toxics.to_vec();
+        }
+        Ok(())
+    }
+
+    async fn load_nonce(&self) -> Result<u64, Error> {
+        Ok(*self.nonce.lock().unwrap())
+    }
+
+    async fn store_nonce(&self, nonce: u64) -> Result<(), Error> {
+        *self.nonce.lock().unwrap() = nonce;
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! Fault-injection "toxics", toxiproxy-style.
+//!
+//! Each proxy carries a [`Vec<Toxic>`] persisted as a JSON column. The
+//! forwarding loop runs both directions through [`pump`], which applies the
+//! per-chunk modifiers (latency, bandwidth) and, once its reader reaches EOF,
+//! `slow_close`; the `timeout` toxic wraps the whole splice. Toxics are snapshotted when a
+//! connection opens, so mutating a proxy's set changes the behavior of
+//! subsequent connections.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Instant;
+
+/// A single traffic-shaping modifier applied to a proxy's forwarded stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum Toxic {
+    /// Delay each forwarded chunk by `ms` milliseconds, ± up to `jitter`.
+    Latency { ms: u64, jitter: u64 },
+    /// Token-bucket rate limit in kilobytes per second.
+    Bandwidth { rate: u64 },
+    /// Delay this long before closing once the peer half-closes.
+    SlowClose { ms: u64 },
+    /// Drop the connection after this many milliseconds regardless of activity.
+    Timeout { ms: u64 },
+}
+
+impl Toxic {
+    /// Reject degenerate configurations that would wedge a connection: a
+    /// zero-rate bandwidth bucket never refills, and a zero-ms timeout drops
+    /// the connection before any data flows.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match *self {
+            Toxic::Bandwidth { rate: 0 } => Err("bandwidth rate must be greater than 0"),
+            Toxic::Timeout { ms: 0 } => Err("timeout ms must be greater than 0"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Stable identifier used to address a toxic in the delete route.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Toxic::Latency { .. } => "latency",
+            Toxic::Bandwidth { .. } => "bandwidth",
+            Toxic::SlowClose { .. } => "slow_close",
+            Toxic::Timeout { .. } => "timeout",
+        }
+    }
+}
+
+/// Connection-level knobs distilled from a toxic set.
+#[derive(Debug, Clone, Default)]
+pub struct Shaper {
+    latency: Option<(u64, u64)>,
+    /// Bytes per second.
+    bandwidth: Option<u64>,
+    slow_close: Option<Duration>,
+    timeout: Option<Duration>,
+}
+
+impl Shaper {
+    /// Collapse a toxic set into the knobs the pump needs.
+    pub fn from_toxics(toxics: &[Toxic]) -> Self {
+        let mut shaper = Shaper::default();
+        for toxic in toxics {
+            match *toxic {
+                Toxic::Latency { ms, jitter } => shaper.latency = Some((ms, jitter)),
+                Toxic::Bandwidth { rate } => shaper.bandwidth = Some(rate * 1024),
+                Toxic::SlowClose { ms } => shaper.slow_close = Some(Duration::from_millis(ms)),
+                Toxic::Timeout { ms } => shaper.timeout = Some(Duration::from_millis(ms)),
+            }
+        }
+        shaper
+    }
+
+    /// Overall connection timeout, if a `timeout` toxic is present.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+/// Copy `reader` into `writer`, applying the per-chunk toxics, then honoring
+/// `slow_close` before shutting the writer down.
+pub async fn pump<R, W>(reader: &mut R, writer: &mut W, shaper: &Shaper) -> std::io::Result<()>
+where
+    R: AsyncReadExt + Unpin,
+    W: AsyncWriteExt + Unpin,
+{
+    let mut buf = [0u8; 16 * 1024];
+    // Token bucket for bandwidth limiting.
+    let mut tokens: u64 = shaper.bandwidth.unwrap_or(0);
+    let mut last_refill = Instant::now();
+
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+
+        if let Some((ms, jitter)) = shaper.latency {
+            tokio::time::sleep(jittered(ms, jitter, n)).await;
+        }
+
+        if let Some(rate) = shaper.bandwidth {
+            let mut remaining = n as u64;
+            while remaining > 0 {
+                let elapsed = last_refill.elapsed();
+                tokens = (tokens + elapsed.as_millis() as u64 * rate / 1000).min(rate);
+                last_refill = Instant::now();
+                if tokens == 0 {
+                    // Bucket is empty: wait a fixed 10 ms for it to refill.
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    continue;
+                }
+                let spend = remaining.min(tokens);
+                tokens -= spend;
+                remaining -= spend;
+            }
+        }
+
+        writer.write_all(&buf[..n]).await?;
+    }
+
+    if let Some(delay) = shaper.slow_close {
+        tokio::time::sleep(delay).await;
+    }
+    writer.shutdown().await
+}
+
+/// Deterministic pseudo-jitter: varies the delay within ±`jitter` without a
+/// random source, keyed off the chunk size so identical traffic is stable.
+fn jittered(ms: u64, jitter: u64, seed: usize) -> Duration {
+    if jitter == 0 {
+        return Duration::from_millis(ms);
+    }
+    let span = jitter * 2 + 1;
+    let offset = (seed as u64 % span) as i64 - jitter as i64;
+    Duration::from_millis((ms as i64 + offset).max(0) as u64)
+}